@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use multimap::MultiMap;
+use rayon::prelude::*;
+use wgpu::{
+    Color, CommandBuffer, CommandEncoder, CommandEncoderDescriptor, Device, LoadOp, Maintain,
+    Operations, Queue, RenderPassColorAttachment, RenderPassDescriptor, SubmissionIndex, Surface,
+    SurfaceError, TextureView, TextureViewDescriptor,
+};
+use winit::dpi::PhysicalSize;
+
+/// Ordering bucket a render pass is grouped into; phases always run in the
+/// order declared here, regardless of registration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Background,
+    Opaque,
+    Transparent,
+    Overlay,
+}
+
+impl Phase {
+    pub fn iter() -> impl Iterator<Item = Phase> {
+        [
+            Phase::Background,
+            Phase::Opaque,
+            Phase::Transparent,
+            Phase::Overlay,
+        ]
+        .into_iter()
+    }
+}
+
+/// A single drawing step the `Renderer` can execute as part of a phase.
+/// Passes record concurrently across threads, so implementations must be
+/// `Send + Sync`. `Renderer` clears `view` once before any pass runs, so
+/// passes always see a pre-cleared view and must use `LoadOp::Load`, never
+/// `LoadOp::Clear`.
+pub trait RenderPass: Send + Sync {
+    fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        viewport: PhysicalSize<u32>,
+        frame_index: usize,
+    );
+}
+
+/// Orders pass indices by declared `Phase` order; `phases[i]` is the phase
+/// the pass registered at index `i` belongs to.
+fn submission_order(phases: &[Phase]) -> Vec<usize> {
+    let mut by_phase: MultiMap<Phase, usize> = MultiMap::new();
+    for (index, phase) in phases.iter().enumerate() {
+        by_phase.insert(*phase, index);
+    }
+
+    Phase::iter()
+        .filter_map(|phase| by_phase.get_vec(&phase))
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Owns the device/queue and a registry of phase-tagged render passes.
+///
+/// This replaces a monolithic `render` method with a pluggable pipeline:
+/// callers register passes via `add_render_pass` and `render` takes care of
+/// grouping them by `Phase` and executing them in declared phase order.
+/// `Device` is `Send + Sync`, so the device is shared as an `Arc` and each
+/// pass records into its own encoder on a rayon worker thread.
+///
+/// To avoid stalling the CPU on the GPU every frame, the renderer paces
+/// itself with `frames_in_flight` submissions live at once: each frame is
+/// assigned a slot in a ring, and recording for a slot only blocks if the
+/// GPU hasn't finished that slot's previous submission yet. Encoders
+/// themselves are still created fresh per pass per frame: `CommandEncoder`
+/// is single-use (`finish()` consumes it), so there's nothing to return to
+/// a pool once a frame's encoders are submitted. Pooling was intentionally
+/// descoped in favor of this frame-pacing scheme.
+pub struct Renderer {
+    device: Arc<Device>,
+    queue: Queue,
+    passes: Vec<(Phase, Box<dyn RenderPass>)>,
+    frames_in_flight: usize,
+    frame_index: usize,
+    in_flight: Vec<Option<SubmissionIndex>>,
+    background: Color,
+}
+
+impl Renderer {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Queue,
+        frames_in_flight: usize,
+        background: Color,
+    ) -> Self {
+        let frames_in_flight = frames_in_flight.max(1);
+        Self {
+            device,
+            queue,
+            passes: Vec::new(),
+            frames_in_flight,
+            frame_index: 0,
+            // `SubmissionIndex` isn't `Clone`, so build the ring without `vec![..; n]`.
+            in_flight: (0..frames_in_flight).map(|_| None).collect(),
+            background,
+        }
+    }
+
+    pub fn add_render_pass(&mut self, pass: Box<dyn RenderPass>, phase: Phase) {
+        self.passes.push((phase, pass));
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    pub fn render(
+        &mut self,
+        surface: &Surface,
+        viewport: PhysicalSize<u32>,
+    ) -> Result<(), SurfaceError> {
+        let slot = self.frame_index % self.frames_in_flight;
+        if let Some(submission) = self.in_flight[slot].take() {
+            self.device
+                .poll(Maintain::WaitForSubmissionIndex(submission));
+        }
+
+        let output = surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
+        let mut clear_encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Renderer Clear Encoder"),
+            });
+        clear_encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Renderer Clear Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(self.background),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let clear_buffer = clear_encoder.finish();
+
+        let phases: Vec<Phase> = self.passes.iter().map(|(phase, _)| *phase).collect();
+
+        // Submission must follow phase order even though passes record
+        // concurrently and may finish out of order, so pair each recorded
+        // buffer with its position in `submission_order` and sort before
+        // handing them to `queue.submit`.
+        let order = submission_order(&phases);
+
+        let frame_index = self.frame_index;
+        let mut buffers: Vec<(usize, CommandBuffer)> = order
+            .par_iter()
+            .enumerate()
+            .map(|(position, &index)| {
+                let (_, pass) = &self.passes[index];
+                let mut encoder = self
+                    .device
+                    .create_command_encoder(&CommandEncoderDescriptor {
+                        label: Some("Renderer Pass Encoder"),
+                    });
+                pass.record(&mut encoder, &view, viewport, frame_index);
+                (position, encoder.finish())
+            })
+            .collect();
+
+        buffers.sort_by_key(|(position, _)| *position);
+        let pass_buffers = buffers.into_iter().map(|(_, buffer)| buffer);
+
+        // The clear runs in its own command buffer, submitted before any
+        // pass, so every pass always sees a pre-cleared view and records
+        // with `LoadOp::Load` instead of clearing itself.
+        let command_buffers = std::iter::once(clear_buffer).chain(pass_buffers);
+
+        self.in_flight[slot] = Some(self.queue.submit(command_buffers));
+        output.present();
+
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_iter_runs_background_to_overlay() {
+        assert_eq!(
+            Phase::iter().collect::<Vec<_>>(),
+            vec![
+                Phase::Background,
+                Phase::Opaque,
+                Phase::Transparent,
+                Phase::Overlay,
+            ]
+        );
+    }
+
+    #[test]
+    fn submission_order_groups_by_phase_regardless_of_registration_order() {
+        let phases = [
+            Phase::Overlay,
+            Phase::Background,
+            Phase::Opaque,
+            Phase::Background,
+        ];
+
+        assert_eq!(submission_order(&phases), vec![1, 3, 2, 0]);
+    }
+}
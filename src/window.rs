@@ -1,9 +1,11 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
 use wgpu::{
-    Backends, Color, CompositeAlphaMode, Device, Features, IndexFormat, Instance, Limits,
-    PowerPreference, PresentMode, PrimitiveTopology, Queue, RenderPassColorAttachment,
-    RenderPipeline, ShaderSource, Surface, SurfaceConfiguration, SurfaceError, TextureUsages,
+    Backends, CommandEncoder, CompositeAlphaMode, Device, Features, IndexFormat, Instance,
+    Limits, PowerPreference, PresentMode, PrimitiveTopology, RenderPassColorAttachment,
+    RenderPipeline, Surface, SurfaceConfiguration, SurfaceError, TextureUsages, TextureView,
 };
 use winit::{
     dpi::PhysicalSize,
@@ -12,21 +14,101 @@ use winit::{
     window::Window,
 };
 
-pub struct Inputs<'a> {
-    pub source: ShaderSource<'a>,
-    pub topology: PrimitiveTopology,
-    pub strip_index_format: Option<IndexFormat>,
+use crate::renderer::{Phase, RenderPass, Renderer};
+
+/// All primitive topologies the example can draw, in the order of the 1-5
+/// number keys that select them.
+const TOPOLOGIES: [PrimitiveTopology; 5] = [
+    PrimitiveTopology::PointList,
+    PrimitiveTopology::LineList,
+    PrimitiveTopology::LineStrip,
+    PrimitiveTopology::TriangleList,
+    PrimitiveTopology::TriangleStrip,
+];
+
+fn strip_index_format_for(topology: PrimitiveTopology) -> Option<IndexFormat> {
+    match topology {
+        PrimitiveTopology::LineStrip | PrimitiveTopology::TriangleStrip => {
+            Some(IndexFormat::Uint32)
+        }
+        _ => None,
+    }
+}
+
+fn topology_name(topology: PrimitiveTopology) -> &'static str {
+    match topology {
+        PrimitiveTopology::PointList => "point-list",
+        PrimitiveTopology::LineList => "line-list",
+        PrimitiveTopology::LineStrip => "line-strip",
+        PrimitiveTopology::TriangleList => "triangle-list",
+        PrimitiveTopology::TriangleStrip => "triangle-strip",
+    }
+}
+
+fn topology_from_arg(primitive_type: &str) -> PrimitiveTopology {
+    match primitive_type {
+        "line-list" => PrimitiveTopology::LineList,
+        "line-strip" => PrimitiveTopology::LineStrip,
+        "triangle-strip" => PrimitiveTopology::TriangleStrip,
+        "triangle-list" => PrimitiveTopology::TriangleList,
+        _ => PrimitiveTopology::PointList,
+    }
+}
+
+fn topology_from_key(keycode: VirtualKeyCode) -> Option<PrimitiveTopology> {
+    match keycode {
+        VirtualKeyCode::Key1 => Some(PrimitiveTopology::PointList),
+        VirtualKeyCode::Key2 => Some(PrimitiveTopology::LineList),
+        VirtualKeyCode::Key3 => Some(PrimitiveTopology::LineStrip),
+        VirtualKeyCode::Key4 => Some(PrimitiveTopology::TriangleList),
+        VirtualKeyCode::Key5 => Some(PrimitiveTopology::TriangleStrip),
+        _ => None,
+    }
+}
+
+/// Draws the example's 9-vertex primitive with the live-switchable topology.
+struct TrianglePass {
+    pipelines: HashMap<PrimitiveTopology, RenderPipeline>,
+    active_topology: Arc<RwLock<PrimitiveTopology>>,
+}
+
+impl RenderPass for TrianglePass {
+    fn record(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        _viewport: PhysicalSize<u32>,
+        _frame_index: usize,
+    ) {
+        let topology = *self.active_topology.read().unwrap();
+        let pipeline = &self.pipelines[&topology];
+
+        // `Renderer` already cleared `view`, so this pass only loads it.
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Triangle Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.draw(0..9, 0..1);
+    }
 }
 
 pub struct State {
     surface: Surface,
     size: PhysicalSize<u32>,
-    background: Color,
     config: SurfaceConfiguration,
-    device: Device,
-    queue: Queue,
+    device: Arc<Device>,
     window: Window,
-    render_pipeline: RenderPipeline,
+    renderer: Renderer,
+    active_topology: Arc<RwLock<PrimitiveTopology>>,
 }
 
 pub async fn run() {
@@ -39,7 +121,7 @@ pub async fn run() {
 
     window.set_title("My Window");
 
-    let mut state = State::new(window).await;
+    let mut state = State::new(window, 2).await;
 
     event_loop.run(move |event, _, control_flow| match event {
         // Have the closure take ownership of the resources.
@@ -93,7 +175,7 @@ pub async fn run() {
 impl State {
     fn update(&mut self) {}
 
-    async fn new(window: Window) -> Self {
+    async fn new(window: Window, frames_in_flight: usize) -> Self {
         let size = window.inner_size();
 
         let instance = Instance::new(Backends::all());
@@ -132,38 +214,24 @@ impl State {
 
         surface.configure(&device, &config);
 
+        let device = Arc::new(device);
+
         let mut primitive_type = "triangle-list";
         let args: Vec<String> = std::env::args().collect();
         if args.len() > 1 {
             primitive_type = &args[1];
         }
 
-        let mut topology = wgpu::PrimitiveTopology::PointList;
-        let mut index_format = None;
-        if primitive_type == "line-list" {
-            topology = wgpu::PrimitiveTopology::LineList;
-            index_format = None;
-        } else if primitive_type == "triangle-list" {
-            topology = wgpu::PrimitiveTopology::TriangleList;
-        } else if primitive_type == "triangle-strip" {
-            topology = wgpu::PrimitiveTopology::TriangleStrip;
-            index_format = Some(wgpu::IndexFormat::Uint32);
-        } else if primitive_type == "line-strip" {
-            topology = wgpu::PrimitiveTopology::LineStrip;
-            index_format = Some(wgpu::IndexFormat::Uint32);
-        }
-
-        window.set_title(&*format!("{}: {}", "Primitive", primitive_type));
-
-        let inputs = Inputs {
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader_triangle.wgsl"))),
-            topology: topology,
-            strip_index_format: index_format,
-        };
+        let initial_topology = topology_from_arg(primitive_type);
+        window.set_title(&format!(
+            "{}: {}",
+            "Primitive",
+            topology_name(initial_topology)
+        ));
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: inputs.source,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader_triangle.wgsl"))),
         });
 
         let render_pipeline_layout =
@@ -173,56 +241,74 @@ impl State {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
+        let pipelines = TOPOLOGIES
+            .into_iter()
+            .map(|topology| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(&format!("Render Pipeline ({})", topology_name(topology))),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: config.format,
+                            blend: Some(wgpu::BlendState {
+                                color: wgpu::BlendComponent::REPLACE,
+                                alpha: wgpu::BlendComponent::REPLACE,
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
                     }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                    primitive: wgpu::PrimitiveState {
+                        topology,
+                        strip_index_format: strip_index_format_for(topology),
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    // If the pipeline will be used with a multiview render pass, this
+                    // indicates how many array layers the attachments will have.
+                    multiview: None,
+                });
+                (topology, pipeline)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let background = wgpu::Color {
+            r: 0.05,
+            g: 0.062,
+            b: 0.08,
+            a: 1.0,
+        };
+
+        let active_topology = Arc::new(RwLock::new(initial_topology));
+
+        let mut renderer = Renderer::new(Arc::clone(&device), queue, frames_in_flight, background);
+        renderer.add_render_pass(
+            Box::new(TrianglePass {
+                pipelines,
+                active_topology: Arc::clone(&active_topology),
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: inputs.topology,
-                strip_index_format: inputs.strip_index_format,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-        });
+            Phase::Opaque,
+        );
 
         Self {
-            background: wgpu::Color {
-                r: 0.05,
-                g: 0.062,
-                b: 0.08,
-                a: 1.0,
-            },
             size,
             surface,
             config,
             device,
-            queue,
             window,
-            render_pipeline,
+            renderer,
+            active_topology,
         }
     }
 
@@ -237,41 +323,7 @@ impl State {
     }
 
     fn render(&mut self) -> Result<(), SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-
-        // actual drawing started here
-        {
-            let mut _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.background),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-            _render_pass.set_pipeline(&self.render_pipeline);
-            _render_pass.draw(0..9, 0..1);
-        }
-
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
+        self.renderer.render(&self.surface, self.size)
     }
 
     fn window(&self) -> &Window {
@@ -290,7 +342,89 @@ impl State {
             //     // };
             //     true
             // }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => self.set_topology(*keycode),
             _ => false,
         }
     }
+
+    /// Swaps the active pipeline for the 1-5 topology keys, updating the
+    /// window title. Returns whether the event was consumed.
+    fn set_topology(&mut self, keycode: VirtualKeyCode) -> bool {
+        let Some(topology) = topology_from_key(keycode) else {
+            return false;
+        };
+
+        *self.active_topology.write().unwrap() = topology;
+        self.window
+            .set_title(&format!("{}: {}", "Primitive", topology_name(topology)));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topology_from_arg_matches_cli_strings() {
+        assert_eq!(topology_from_arg("line-list"), PrimitiveTopology::LineList);
+        assert_eq!(topology_from_arg("line-strip"), PrimitiveTopology::LineStrip);
+        assert_eq!(
+            topology_from_arg("triangle-list"),
+            PrimitiveTopology::TriangleList
+        );
+        assert_eq!(
+            topology_from_arg("triangle-strip"),
+            PrimitiveTopology::TriangleStrip
+        );
+        assert_eq!(topology_from_arg("nonsense"), PrimitiveTopology::PointList);
+    }
+
+    #[test]
+    fn topology_from_key_maps_number_keys_one_through_five() {
+        assert_eq!(
+            topology_from_key(VirtualKeyCode::Key1),
+            Some(PrimitiveTopology::PointList)
+        );
+        assert_eq!(
+            topology_from_key(VirtualKeyCode::Key2),
+            Some(PrimitiveTopology::LineList)
+        );
+        assert_eq!(
+            topology_from_key(VirtualKeyCode::Key3),
+            Some(PrimitiveTopology::LineStrip)
+        );
+        assert_eq!(
+            topology_from_key(VirtualKeyCode::Key4),
+            Some(PrimitiveTopology::TriangleList)
+        );
+        assert_eq!(
+            topology_from_key(VirtualKeyCode::Key5),
+            Some(PrimitiveTopology::TriangleStrip)
+        );
+        assert_eq!(topology_from_key(VirtualKeyCode::Key6), None);
+    }
+
+    #[test]
+    fn strip_index_format_only_set_for_strip_topologies() {
+        assert_eq!(
+            strip_index_format_for(PrimitiveTopology::LineStrip),
+            Some(IndexFormat::Uint32)
+        );
+        assert_eq!(
+            strip_index_format_for(PrimitiveTopology::TriangleStrip),
+            Some(IndexFormat::Uint32)
+        );
+        assert_eq!(strip_index_format_for(PrimitiveTopology::PointList), None);
+        assert_eq!(strip_index_format_for(PrimitiveTopology::LineList), None);
+        assert_eq!(strip_index_format_for(PrimitiveTopology::TriangleList), None);
+    }
 }